@@ -0,0 +1,108 @@
+// Generates compile-time tables for the ESIL parser from
+// `src/frontend/esil/esil_ops.in`, mirroring how bytecode crates generate
+// `ops.rs`/`instrs.rs` from an `instructions.in`. Each row in the spec file
+// carries a mnemonic, its `Arity`, and the base operator it lowers to if it
+// is a composite assignment (e.g. `^=` lowers to `^`). We emit:
+//
+//   * `opset.rs`   - a `phf::Map` of basic-operator mnemonics to `Operator`.
+//   * `regset.rs`  - a `phf::Map` of register names to their size in bits,
+//     taken from the `REGS` list below rather than from the spec file.
+//   * `composites.rs` - a match-based `expand_composite()` that resolves a
+//     composite-assignment mnemonic to the basic operator it lowers to,
+//     replacing the ad hoc re-splitting on `'='` that used to live in
+//     `parse()`.
+//
+// Adding a new ESIL op (or a new composite assignment) is a one-line edit
+// to `esil_ops.in`, not scattered changes across the parser.
+
+extern crate phf_codegen;
+
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+struct OpRow {
+    mnemonic: String,
+    arity: String,
+    composite_of: Option<String>,
+}
+
+fn read_ops() -> Vec<OpRow> {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let spec_path = Path::new(&manifest_dir).join("src/frontend/esil/esil_ops.in");
+    let spec = std::fs::read_to_string(&spec_path).unwrap();
+
+    let mut rows = Vec::new();
+    for line in spec.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut cols = line.split_whitespace();
+        let mnemonic = cols.next().unwrap().to_string();
+        let arity = cols.next().unwrap().to_string();
+        let composite_of = match cols.next().unwrap() {
+            "_" => None,
+            base => Some(base.to_string()),
+        };
+        rows.push(OpRow { mnemonic, arity, composite_of });
+    }
+    rows
+}
+
+// (register name, size in bits).
+static REGS: &[(&str, u8)] = &[
+    ("rax", 64),
+    ("rbx", 64),
+    ("rcx", 64),
+    ("rdx", 64),
+    ("rsp", 64),
+    ("rbp", 64),
+    ("rsi", 64),
+    ("rdi", 64),
+    ("rip", 64),
+];
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let ops = read_ops();
+
+    let opset_path = Path::new(&out_dir).join("opset.rs");
+    let mut opset_out = BufWriter::new(File::create(&opset_path).unwrap());
+    let mut opset_map = phf_codegen::Map::new();
+    for row in ops.iter().filter(|r| r.composite_of.is_none()) {
+        opset_map.entry(
+            &row.mnemonic[..],
+            &format!("Operator::new(\"{}\", Arity::{})", row.mnemonic, row.arity),
+        );
+    }
+    writeln!(
+        &mut opset_out,
+        "static OPSET: phf::Map<&'static str, Operator<'static>> = {};",
+        opset_map.build()
+    ).unwrap();
+
+    let regset_path = Path::new(&out_dir).join("regset.rs");
+    let mut regset_out = BufWriter::new(File::create(&regset_path).unwrap());
+    let mut regset_map = phf_codegen::Map::new();
+    for &(reg, size) in REGS {
+        regset_map.entry(reg, &size.to_string());
+    }
+    writeln!(
+        &mut regset_out,
+        "static REGSET: phf::Map<&'static str, u8> = {};",
+        regset_map.build()
+    ).unwrap();
+
+    let composites_path = Path::new(&out_dir).join("composites.rs");
+    let mut composites_out = BufWriter::new(File::create(&composites_path).unwrap());
+    writeln!(&mut composites_out, "fn expand_composite(token: &str) -> Option<&'static str> {{").unwrap();
+    writeln!(&mut composites_out, "    match token {{").unwrap();
+    for row in ops.iter().filter_map(|r| r.composite_of.as_ref().map(|base| (r, base))) {
+        writeln!(&mut composites_out, "        \"{}\" => Some(\"{}\"),", (row.0).mnemonic, row.1).unwrap();
+    }
+    writeln!(&mut composites_out, "        _ => None,").unwrap();
+    writeln!(&mut composites_out, "    }}").unwrap();
+    writeln!(&mut composites_out, "}}").unwrap();
+}
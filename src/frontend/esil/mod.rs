@@ -21,7 +21,7 @@
 //! ```
 //! let esil = String::from("eax,ebx,^=");
 //! let p = Parser::new();
-//! p.parse(esil)
+//! p.parse(&esil).unwrap();
 //! for inst in &p.emit_insts() {
 //!     println!("{}", inst.to_string());
 //! }
@@ -30,7 +30,7 @@
 
 use std::collections::HashMap;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum Arity {
     Zero,
     Unary,
@@ -49,14 +49,19 @@ impl Arity {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+// `&'a str` serializes fine, so `Operator` derives `Serialize`. It can't
+// derive `Deserialize`, though: a deserializer only produces owned data, and
+// `op` would have nothing to borrow from. `Parser::from_insts()` works
+// around this by deserializing into `OwnedOperator` (below) and resolving
+// the mnemonic against a `Parser`'s opset to get back a borrowed `Operator`.
+#[derive(Debug, Copy, Clone, Serialize)]
 pub struct Operator<'a> {
     op: &'a str,
     arity: Arity,
 }
 
 impl<'a> Operator<'a> {
-    pub fn new(op: &str, n: Arity) -> Operator {
+    pub const fn new(op: &'a str, n: Arity) -> Operator<'a> {
         Operator { op: op, arity: n }
     }
 
@@ -65,7 +70,7 @@ impl<'a> Operator<'a> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum Location {
     Memory,
     Register,
@@ -75,7 +80,7 @@ pub enum Location {
     Null,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Value is used to represent operands to an operator in a statement.
 pub struct Value {
     /// Name
@@ -86,6 +91,8 @@ pub struct Value {
     location: Location,
     /// Value if evaluable.
     value: i64,
+    /// Original source text for a constant (e.g. "0x204db1"), empty if unset.
+    raw: String,
     // TODO: Convert from u32 to TypeSet.
     // Every value can be considered in terms of typesets rather than fixed
     // types which can then be narrowed down based on the analysis.
@@ -94,26 +101,35 @@ pub struct Value {
 }
 
 impl Value {
-    pub fn new(name: String, size: u8, location: Location, value: i64, typeset: u32) -> Value {
+    pub fn new(name: String, size: u8, location: Location, value: i64, typeset: u32, raw: String) -> Value {
         Value {
             name: name.clone(),
             size: size,
             location: location,
             value: value,
+            raw: raw,
             typeset: typeset,
         }
     }
 
     pub fn null() -> Value {
-        Value::new("".to_string(), 0, Location::Null, 0, 0)
+        Value::new("".to_string(), 0, Location::Null, 0, 0, String::new())
     }
 
     pub fn tmp(i: u64) -> Value {
-        Value::new(format!("tmp_{:x}", i), 0, Location::Temporary, 0, 0)
+        Value::new(format!("tmp_{:x}", i), 0, Location::Temporary, 0, 0, String::new())
+    }
+
+    pub fn to_string(&self) -> String {
+        if !self.raw.is_empty() {
+            self.raw.clone()
+        } else {
+            self.name.clone()
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Instruction<'a> {
     opcode: Operator<'a>,
     dest: Value,
@@ -121,6 +137,24 @@ pub struct Instruction<'a> {
     operand_2: Value,
 }
 
+// Owned stand-in for `Operator`, used to deserialize an `Instruction` dumped
+// by `Parser::emit_json()`. Stores the mnemonic as a `String` since a
+// deserializer has nothing to borrow `&'a str` from; `Parser::from_insts()`
+// resolves `op` back against its opset to reconstruct a borrowed `Operator`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OwnedOperator {
+    op: String,
+    arity: Arity,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OwnedInstruction {
+    opcode: OwnedOperator,
+    dest: Value,
+    operand_1: Value,
+    operand_2: Value,
+}
+
 impl<'a> Instruction<'a> {
     pub fn new(opcode: Operator<'a>, dest: Value, op1: Value, op2: Value) -> Instruction<'a> {
         Instruction {
@@ -132,87 +166,160 @@ impl<'a> Instruction<'a> {
     }
     pub fn to_string(&self) -> String {
         if self.opcode.op == "=" {
-            format!("{} {} {}", self.operand_1.name, self.opcode.op, self.operand_2.name)
+            format!("{} {} {}", self.operand_1.to_string(), self.opcode.op, self.operand_2.to_string())
         } else {
-            format!("{} {} {} {} {}", self.dest.name, "=", self.operand_1.name, self.opcode.op, self.operand_2.name)
+            format!("{} {} {} {} {}", self.dest.to_string(), "=", self.operand_1.to_string(), self.opcode.op, self.operand_2.to_string())
         }
     }
 }
 
-macro_rules! hash {
-    ( $( ($x:expr, $y:expr) ),* ) => {
-        {
-            let mut temp_hash = HashMap::new();
-            $(
-                temp_hash.insert($x, $y);
-             )*
-            temp_hash
-        }
+// Perfect-hash tables for the ESIL operator and register sets, generated at
+// build time by `build.rs` (operators and composite-lowering rules from
+// `esil_ops.in`, registers from its `REGS` list). Using `phf` here means opcode and
+// register lookup are constant-time matches baked into the binary, rather
+// than a `HashMap` rebuilt on every `Parser::new()` call.
+include!(concat!(env!("OUT_DIR"), "/opset.rs"));
+include!(concat!(env!("OUT_DIR"), "/regset.rs"));
+include!(concat!(env!("OUT_DIR"), "/composites.rs"));
+
+// Parses an ESIL operand token as an integer literal. Unlike a plain
+// `str::parse::<i64>()`, this recognizes the `0x`/`0X` hex, `0o` octal and
+// `0b` binary prefixes radare2 emits, as well as a leading `-` for
+// negatives.
+fn parse_literal(token: &str) -> Option<i64> {
+    let (neg, rest) = match token.strip_prefix('-') {
+        Some(stripped) => (true, stripped),
+        None => (false, token),
+    };
+
+    let val = if let Some(hex) = rest.strip_prefix("0x").or_else(|| rest.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(oct) = rest.strip_prefix("0o") {
+        i64::from_str_radix(oct, 8).ok()
+    } else if let Some(bin) = rest.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        rest.parse::<i64>().ok()
     };
+
+    val.map(|v| if neg { -v } else { v })
+}
+
+/// Identifies a `BasicBlock` within the graph `Parser::emit_cfg()` returns.
+pub type BlockId = usize;
+
+/// How a `BasicBlock` reaches one of its successors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EdgeKind {
+    /// Reached unconditionally, by running off the end of the block.
+    Fallthrough,
+    /// Reached when a preceding `?{`'s predicate is true.
+    Taken,
+    /// Reached when a preceding `?{`'s predicate is false.
+    NotTaken,
 }
 
-fn init_opset() -> HashMap<&'static str, Operator<'static>> {
-    // Make a map from esil string to struct Operator.
-    // (operator: &str, arity: Arity).
-    // Possible Optimization:  Move to compile-time generation ?
-    hash![
-        ("==" , Operator::new("==", Arity::Binary)),
-        ("<"  , Operator::new("<" , Arity::Binary)),
-        (">"  , Operator::new(">" , Arity::Binary)),
-        ("<=" , Operator::new("<=", Arity::Binary)),
-        (">=" , Operator::new(">=", Arity::Binary)),
-        ("<<" , Operator::new("<<", Arity::Binary)),
-        (">>" , Operator::new(">>", Arity::Binary)),
-        ("&"  , Operator::new("&" , Arity::Binary)),
-        ("|"  , Operator::new("|" , Arity::Binary)),
-        ("="  , Operator::new("=" , Arity::Binary)),
-        ("*"  , Operator::new("*" , Arity::Binary)),
-        ("^"  , Operator::new("^" , Arity::Binary)),
-        ("+"  , Operator::new("+" , Arity::Binary)),
-        ("-"  , Operator::new("-" , Arity::Binary)),
-        ("/"  , Operator::new("/" , Arity::Binary)),
-        ("%"  , Operator::new("%" , Arity::Binary)),
-        ("?{" , Operator::new("?{", Arity::Unary)),
-        ("!"  , Operator::new("!" , Arity::Unary)),
-        ("--" , Operator::new("--", Arity::Unary)),
-        ("++" , Operator::new("++", Arity::Unary)),
-        ("}"  , Operator::new("}" , Arity::Zero))
-    ]
+/// A straight-line run of instructions with no internal control flow, and
+/// the edges leaving it. Produced by `Parser::emit_cfg()` by splitting the
+/// instruction stream at ESIL `?{`/`}` conditional boundaries.
+// Only `Serialize`, not `Deserialize`: it holds `Instruction<'a>`, which has
+// the same borrowed-mnemonic problem as `Operator` (see above).
+#[derive(Debug, Clone, Serialize)]
+pub struct BasicBlock<'a> {
+    pub insts: Vec<Instruction<'a>>,
+    pub succ: Vec<(EdgeKind, BlockId)>,
 }
 
-fn init_regset() -> HashMap<&'static str, u8> {
-    // Use from sdb later, probably a better option.
-    hash![
-        ("rax", 64),
-        ("rbx", 64),
-        ("rcx", 64),
-        ("rdx", 64),
-        ("rsp", 64),
-        ("rbp", 64),
-        ("rsi", 64),
-        ("rdi", 64),
-        ("rip", 64)
-    ]
+impl<'a> BasicBlock<'a> {
+    fn new() -> BasicBlock<'a> {
+        BasicBlock { insts: Vec::new(), succ: Vec::new() }
+    }
 }
 
 pub struct Parser<'a> {
     stack: Vec<Value>,
     insts: Vec<Instruction<'a>>,
-    opset: HashMap<&'a str, Operator<'a>>,
-    regset: HashMap<&'a str, u8>,
     tmp_index: u64,
     default_size: u8,
+    // User-registered operators/composites, consulted before the builtin
+    // `OPSET`/`expand_composite` tables so callers can extend (or, with
+    // `override_builtin`, override) the grammar the parser understands.
+    user_opset: HashMap<&'a str, Operator<'a>>,
+    user_composites: HashMap<&'a str, &'a str>,
+    // CFG being built alongside the flat `insts` list: `blocks[current_block]`
+    // receives every instruction `add_inst()` emits, `block_stack` holds the
+    // head block of each `?{` still awaiting its `}`, for EdgeKind::NotTaken.
+    blocks: Vec<BasicBlock<'a>>,
+    current_block: BlockId,
+    block_stack: Vec<BlockId>,
+    cfg_error: Option<String>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new() -> Parser<'a> {
-        Parser { 
+        Parser {
             stack: Vec::new(),
             insts: Vec::new(),
-            opset: init_opset(),
-            regset: init_regset(),
             tmp_index: 0,
             default_size: 64,
+            user_opset: HashMap::new(),
+            user_composites: HashMap::new(),
+            blocks: vec![BasicBlock::new()],
+            current_block: 0,
+            block_stack: Vec::new(),
+            cfg_error: None,
+        }
+    }
+
+    /// Constructs a `Parser` seeded with a caller-supplied operator table,
+    /// e.g. for a radare2 ESIL extension the builtin `OPSET` doesn't know
+    /// about. Entries here take priority over the builtin table, so this
+    /// can also be used to override a builtin mnemonic's arity. The one
+    /// exception is `}`: `parse()` always treats it as the end of a
+    /// conditional and never looks it up in either table.
+    pub fn with_opset(opset: HashMap<&'a str, Operator<'a>>) -> Parser<'a> {
+        let mut p = Parser::new();
+        p.user_opset = opset;
+        p
+    }
+
+    /// Teaches the parser a new ESIL mnemonic. Fails if `mnemonic` already
+    /// names a builtin operator, unless `override_builtin` is set.
+    pub fn register_operator(&mut self, mnemonic: &'a str, arity: Arity, override_builtin: bool) -> Result<(), String> {
+        if !override_builtin && OPSET.get(mnemonic).is_some() {
+            return Err(format!("'{}' is a builtin operator; pass override_builtin = true to replace it", mnemonic));
+        }
+        self.user_opset.insert(mnemonic, Operator::new(mnemonic, arity));
+        Ok(())
+    }
+
+    /// Teaches the parser a new composite-assignment mnemonic (e.g. an
+    /// architecture-specific `<<<=`), lowered to `base_op` followed by `=`.
+    /// Fails if `mnemonic` already names a builtin composite, unless
+    /// `override_builtin` is set.
+    pub fn register_composite(&mut self, mnemonic: &'a str, base_op: &'a str, override_builtin: bool) -> Result<(), String> {
+        if !override_builtin && expand_composite(mnemonic).is_some() {
+            return Err(format!("'{}' is a builtin composite operator; pass override_builtin = true to replace it", mnemonic));
+        }
+        self.user_composites.insert(mnemonic, base_op);
+        Ok(())
+    }
+
+    // Looks up a mnemonic in the user-registered table first, falling back
+    // to the builtin `OPSET`.
+    fn lookup_op(&self, mnemonic: &str) -> Option<Operator<'a>> {
+        match self.user_opset.get(mnemonic) {
+            Some(op) => Some(*op),
+            None => OPSET.get(mnemonic).copied(),
+        }
+    }
+
+    // Looks up a composite-assignment mnemonic's base operator, consulting
+    // user-registered composites before the builtin lowering rules.
+    fn lookup_composite(&self, mnemonic: &str) -> Option<&'a str> {
+        match self.user_composites.get(mnemonic) {
+            Some(base) => Some(*base),
+            None => expand_composite(mnemonic),
         }
     }
 
@@ -237,19 +344,63 @@ impl<'a> Parser<'a> {
             "=" => Value::null(),
             _ => self.get_tmp_register(),
         };
-        self.insts.push(Instruction::new(op, dst.clone(), op2, op1));
+        let inst = Instruction::new(op, dst.clone(), op2, op1);
+        self.insts.push(inst.clone());
+        self.blocks[self.current_block].insts.push(inst);
         self.stack.push(dst);
     }
 
-    pub fn parse(&mut self, esil: &'a str) {
+    // Starts a new basic block for the body of a `?{`, and records the
+    // `Taken` edge from the block it interrupted.
+    fn open_conditional(&mut self) {
+        let head = self.current_block;
+        self.blocks.push(BasicBlock::new());
+        let taken = self.blocks.len() - 1;
+        self.blocks[head].succ.push((EdgeKind::Taken, taken));
+        self.block_stack.push(head);
+        self.current_block = taken;
+    }
+
+    // Closes the innermost open `?{`, rejoining its `Taken` and `NotTaken`
+    // paths into a new basic block. Fails if there is no matching `?{`.
+    fn close_conditional(&mut self) -> Result<(), String> {
+        let head = match self.block_stack.pop() {
+            Some(head) => head,
+            None => return Err(String::from("unbalanced ESIL: '}' has no matching '?{'")),
+        };
+        self.blocks.push(BasicBlock::new());
+        let rejoin = self.blocks.len() - 1;
+        self.blocks[self.current_block].succ.push((EdgeKind::Fallthrough, rejoin));
+        self.blocks[head].succ.push((EdgeKind::NotTaken, rejoin));
+        self.current_block = rejoin;
+        Ok(())
+    }
+
+    pub fn parse(&mut self, esil: &'a str) -> Result<(), String> {
         for token in esil.split(',') {
-            let op = match self.opset.get(token) {
-                Some(op) => op.clone(),
+            // `}` only closes the current conditional's basic block; unlike
+            // every other token it doesn't pop an operand or emit an
+            // `Instruction`, so it's handled before reaching `add_inst()`.
+            if token == "}" {
+                if let Err(e) = self.close_conditional() {
+                    self.cfg_error = Some(e.clone());
+                    return Err(e);
+                }
+                continue;
+            }
+
+            let op = match self.lookup_op(token) {
+                Some(op) => op,
                 None => Operator::nop(),
             };
 
             if op.op != "nop" {
                 self.add_inst(op);
+                // `?{` also delimits a basic block: it starts a conditional's
+                // body, on top of recording the instruction itself above.
+                if token == "?{" {
+                    self.open_conditional();
+                }
                 continue;
             }
 
@@ -257,47 +408,213 @@ impl<'a> Parser<'a> {
                 // Treat it as a operand.
                 let mut val_type = Location::Unknown;
                 let mut val: i64 = 0;
+                let mut raw = String::new();
                 // Change this default based on arch.
                 let mut size: u8 = self.default_size;
-                if let Some(s) = self.regset.get(token) {
+                if let Some(s) = REGSET.get(token) {
                     val_type = Location::Register;
                     // For now, reg is just a u8.
-                    size = *s; 
-                } else if let Ok(v) = token.parse::<i64>() {
+                    size = *s;
+                } else if let Some(v) = parse_literal(token) {
                     val_type = Location::Constant;
                     val = v;
+                    raw = String::from(token);
                 }
 
-                let v = Value::new(String::from(token), size, val_type, val, 0);
+                let v = Value::new(String::from(token), size, val_type, val, 0, raw);
                 self.stack.push(v);
                 continue;
             }
 
-            // Expand the 'composite' operators to 'basic' ones.
-            for t in token.split_terminator('=') {
-                let o = match self.opset.get(t) {
-                    Some(op) => op.clone(),
-                    None => Operator::nop(), 
-                };
-                if o.op == "nop" {
-                    // Return error here instead.
-                    return;
-                }
-                let dst = self.stack.last().unwrap().clone();
-                self.add_inst(o);
-                self.stack.push(dst);
-                self.add_inst(Operator::new("=", Arity::Binary));
+            // Expand the 'composite' operator (e.g. `^=`) to its basic
+            // operator followed by an assignment, using the lowering rule
+            // generated from `esil_ops.in` (or a user-registered one).
+            let base = match self.lookup_composite(token) {
+                Some(base) => base,
+                None => return Err(format!("unknown ESIL token '{}'", token)),
+            };
+            let o = match self.lookup_op(base) {
+                Some(op) => op,
+                None => Operator::nop(),
+            };
+            if o.op == "nop" {
+                return Err(format!("composite '{}' lowers to unknown operator '{}'", token, base));
             }
+            let dst = match self.stack.last() {
+                Some(v) => v.clone(),
+                None => return Err(format!("stack underflow: '{}' has no operand to assign to", token)),
+            };
+            self.add_inst(o);
+            self.stack.push(dst);
+            self.add_inst(Operator::new("=", Arity::Binary));
         }
+        Ok(())
     }
 
     pub fn emit_insts(&self) -> Vec<Instruction<'a>> {
         (self).insts.clone()
     }
+
+    /// Dumps the instructions emitted so far as JSON, so they can be stored,
+    /// diffed, or handed to an external analysis pass and reloaded later
+    /// with `from_insts()`.
+    pub fn emit_json(&self) -> Result<String, String> {
+        serde_json::to_string(&self.insts).map_err(|e| e.to_string())
+    }
+
+    /// Loads instructions previously dumped by `emit_json()`, appending them
+    /// to this parser's instruction stream. Each mnemonic is resolved
+    /// against this parser's opset (builtin and user-registered), so
+    /// loading a dump that uses an operator this parser doesn't know about
+    /// fails rather than producing a bogus `Instruction`.
+    ///
+    /// A dump only carries the flat `Instruction` list, not the `?{`/`}`
+    /// boundaries `parse()` splits into basic blocks as it goes (by the time
+    /// an `Instruction` exists, a matching `}` has already been folded away;
+    /// see `close_conditional()`). So `emit_cfg()` has nothing correct to
+    /// rebuild from afterwards, and is disabled for the rest of this
+    /// `Parser`'s lifetime once `from_insts()` has been called.
+    pub fn from_insts(&mut self, json: &str) -> Result<(), String> {
+        let owned: Vec<OwnedInstruction> = match serde_json::from_str(json) {
+            Ok(owned) => owned,
+            Err(e) => return Err(e.to_string()),
+        };
+        for inst in owned {
+            let op = match self.lookup_op(&inst.opcode.op) {
+                Some(op) => op,
+                None => return Err(format!("unknown operator '{}' while loading instructions", inst.opcode.op)),
+            };
+            self.insts.push(Instruction::new(op, inst.dest, inst.operand_1, inst.operand_2));
+        }
+        self.cfg_error = Some(String::from(
+            "emit_cfg() is unsupported once from_insts() has loaded a dump: \
+             a flat Instruction list doesn't carry the '?{'/'}' boundaries needed to rebuild basic blocks",
+        ));
+        Ok(())
+    }
+
+    /// Returns the basic-block graph built by splitting the parsed
+    /// instruction stream at `?{`/`}` boundaries, suitable for dataflow
+    /// analysis. Fails if the ESIL had an unbalanced `?{`/`}` nesting, or if
+    /// `from_insts()` has loaded a dump on this `Parser` (see its docs).
+    pub fn emit_cfg(&self) -> Result<Vec<BasicBlock<'a>>, String> {
+        if let Some(ref e) = self.cfg_error {
+            return Err(e.clone());
+        }
+        if !self.block_stack.is_empty() {
+            return Err(String::from("unbalanced ESIL: missing '}' for an open '?{'"));
+        }
+        Ok(self.blocks.clone())
+    }
 }
 
 #[test]
 fn testing() {
 	let mut p = Parser::new();
-	p.parse("0,0x204db1,rip,+,[1],==,%z,zf,=,%b8,cf,=,%p,pf,=,%s,sf,=");
+	p.parse("0,0x204db1,rip,+,[1],==,%z,zf,=,%b8,cf,=,%p,pf,=,%s,sf,=").unwrap();
+}
+
+#[test]
+fn literal_tokenization() {
+	assert_eq!(parse_literal("0x204db1"), Some(0x204db1));
+	assert_eq!(parse_literal("0X204DB1"), Some(0x204db1));
+	assert_eq!(parse_literal("0o17"), Some(0o17));
+	assert_eq!(parse_literal("0b101"), Some(0b101));
+	assert_eq!(parse_literal("-42"), Some(-42));
+	assert_eq!(parse_literal("42"), Some(42));
+	assert_eq!(parse_literal("eax"), None);
+
+	let mut p = Parser::new();
+	p.parse("0x204db1,rax,+,=").unwrap();
+	let insts = p.emit_insts();
+	assert_eq!(insts[0].operand_2.raw, "0x204db1");
+	assert!(insts[0].to_string().contains("0x204db1"));
+}
+
+#[test]
+fn custom_operators() {
+	let mut p = Parser::new();
+	assert!(p.register_operator("==", Arity::Binary, false).is_err());
+	assert!(p.register_operator("$$", Arity::Unary, false).is_ok());
+
+	p.parse("rax,$$").unwrap();
+	let insts = p.emit_insts();
+	assert_eq!(insts[0].opcode.op, "$$");
+
+	assert!(p.register_composite("^=", "^", false).is_err());
+	assert!(p.register_composite("<<<=", "<<", false).is_ok());
+
+	p.parse("rbx,rax,<<<=").unwrap();
+	let insts2 = p.emit_insts();
+	assert_eq!(insts2[insts2.len() - 1].opcode.op, "=");
+}
+
+#[test]
+fn json_roundtrip() {
+	let mut p = Parser::new();
+	p.parse("eax,ebx,^=").unwrap();
+	let json = p.emit_json().unwrap();
+
+	let mut p2 = Parser::new();
+	p2.from_insts(&json).unwrap();
+	assert_eq!(p2.emit_insts().len(), p.emit_insts().len());
+	assert_eq!(p2.emit_insts()[0].opcode.op, p.emit_insts()[0].opcode.op);
+
+	let mut p3 = Parser::new();
+	let bogus = json.replace("\"^\"", "\"nonexistent_op\"");
+	assert!(p3.from_insts(&bogus).is_err());
+}
+
+#[test]
+fn from_insts_disables_cfg() {
+	let mut p = Parser::new();
+	p.parse("rax,rbx,==,?{,rax,rbx,=,}").unwrap();
+	let json = p.emit_json().unwrap();
+	assert!(p.emit_cfg().is_ok());
+
+	// Reloading the same dump into a fresh `Parser` can't recover the `?{`/`}`
+	// boundaries `parse()` tracked, so its `emit_cfg()` must refuse instead of
+	// silently returning a single-block graph that drops the control flow.
+	let mut p2 = Parser::new();
+	p2.from_insts(&json).unwrap();
+	assert!(p2.emit_cfg().is_err());
+}
+
+#[test]
+fn cfg_nested_conditionals() {
+	let mut p = Parser::new();
+	p.parse("rax,rbx,==,?{,rax,rbx,=,rcx,rdx,==,?{,rcx,rdx,=,},}").unwrap();
+	let blocks = p.emit_cfg().unwrap();
+
+	assert_eq!(blocks.len(), 5);
+	assert_eq!(blocks[0].succ, vec![(EdgeKind::Taken, 1), (EdgeKind::NotTaken, 4)]);
+	assert_eq!(blocks[1].succ, vec![(EdgeKind::Taken, 2), (EdgeKind::NotTaken, 3)]);
+	assert_eq!(blocks[2].succ, vec![(EdgeKind::Fallthrough, 3)]);
+	assert_eq!(blocks[3].succ, vec![(EdgeKind::Fallthrough, 4)]);
+	assert_eq!(blocks[4].succ, vec![]);
+}
+
+#[test]
+fn cfg_close_conditional_does_not_touch_operand_stack() {
+	let mut p = Parser::new();
+	p.parse("rax,rbx,==,?{,rcx,}").unwrap();
+	let blocks = p.emit_cfg().unwrap();
+
+	// `}` must close the block only, not emit a bogus `Instruction` or pop
+	// the `rcx` pushed just before it.
+	assert_eq!(blocks[1].insts.len(), 0);
+	assert_eq!(p.emit_insts().len(), 2);
+	assert_eq!(p.emit_insts()[1].opcode.op, "?{");
+	assert_eq!(p.stack.last().unwrap().name, "rcx");
+}
+
+#[test]
+fn cfg_unbalanced_braces() {
+	let mut p = Parser::new();
+	assert!(p.parse("rax,}").is_err());
+	assert!(p.emit_cfg().is_err());
+
+	let mut p2 = Parser::new();
+	p2.parse("rax,rbx,==,?{,rax,rbx,=").unwrap();
+	assert!(p2.emit_cfg().is_err());
 }
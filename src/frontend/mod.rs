@@ -0,0 +1,3 @@
+// TODO: Add License information.
+
+pub mod esil;
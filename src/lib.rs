@@ -0,0 +1,15 @@
+// TODO: Add License information.
+
+//! radeco-lib: crate root.
+//!
+//! `extern crate` declarations that load macros (like `serde_derive`'s
+//! custom derives) must live here rather than in a nested module, so this
+//! is also where the crate's third-party dependencies are declared.
+
+extern crate phf;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+pub mod frontend;